@@ -23,7 +23,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 
     group.bench_function("remedian not full", |b| {
         b.iter(|| {
-            let mut remedian = RemedianBlock::default();
+            let mut remedian: RemedianBlock<f32> = RemedianBlock::default();
 
             for v in data.iter() {
                 remedian.add_sample_point(*v);
@@ -35,7 +35,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 
     group.bench_function("remedian full", |b| {
         b.iter(|| {
-            let mut remedian = RemedianBlock::new(11, 3);
+            let mut remedian: RemedianBlock<f32> = RemedianBlock::new(11, 3);
 
             for v in data.iter() {
                 if !remedian.add_sample_point(*v) {
@@ -37,7 +37,7 @@ const DATA: [Class; 15] = [
 ];
 
 fn main() {
-    let mut remedian = RemedianBlock::default();
+    let mut remedian: RemedianBlock<Class> = RemedianBlock::default();
 
     // Read data points from our data source, and fold them into the remedian
     // It just works!
@@ -17,7 +17,7 @@ const DATA: [f32; 15] = [
 fn main() {
     // Construct an empty remedian block, with a custom base and exponent
     // These values will result in a block which uses 20 f32's of space, but can handle up to 625 sample points
-    let mut remedian = RemedianBlock::new(5, 4);
+    let mut remedian: RemedianBlock<f32> = RemedianBlock::new(5, 4);
 
     // Read data points from our data source, and fold them into the remedian
     for data_point in DATA {
@@ -16,7 +16,7 @@ fn main() {
     // The default block is configured with a reasonable size
     // It can account for roughly 25 billion sample points before running out of space
     // But it stores at most 110 f32's at a time
-    let mut remedian = RemedianBlock::default();
+    let mut remedian: RemedianBlock<f32> = RemedianBlock::default();
 
     // Read data points from our data source, and fold them into the remedian
     for data_point in DATA {
@@ -25,5 +25,5 @@ fn main() {
 
     // Get our (approximate) answer
     let median = remedian.median();
-    println!("Approximated the median as: {median}");
+    println!("Approximated the median as: {median:?}");
 }
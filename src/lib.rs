@@ -1,7 +1,228 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![doc = include_str!("../README.md")]
 #![warn(missing_docs)]
 
-use std::cmp::Ordering;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{cmp::Ordering, mem};
+
+/// The result of an approximate median calculation
+///
+/// For an even number of collected sample points, the true median lies between two middle
+/// elements. Rather than silently averaging them (which would require a numeric trait bound
+/// on `T`), [`RemedianBlock::median_pair`] returns both and leaves the combination up to the
+/// caller.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MedianResult<T> {
+    /// A single middle value
+    One(T),
+    /// Two straddling middle values, with `.0 <= .1`
+    Two(T, T),
+}
+
+/// A growable, sortable collection used to back a single batch row in a [`RemedianBlock`]'s
+/// scratch matrix
+///
+/// This captures the small slice of [`Vec`]'s API that remedian actually needs, so a batch can
+/// be backed by an alternative collection type instead of committing to [`Vec`] specifically.
+/// [`Vec`] implements this trait, and is the default backing type, so existing users of
+/// [`RemedianBlock<T>`] are unaffected. Enable the `smallvec` feature to back batches with
+/// [`smallvec::SmallVec`] instead, which avoids any heap allocation for batches that fit inline.
+pub trait BatchStorage<T>: Default {
+    /// Constructs an empty collection with room for at least `capacity` elements
+    fn with_capacity(capacity: usize) -> Self;
+
+    /// Appends an element to the end of the collection
+    fn push(&mut self, value: T);
+
+    /// Number of elements currently stored
+    fn len(&self) -> usize;
+
+    /// Whether the collection currently holds no elements
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all elements, without necessarily freeing any backing allocation
+    fn clear(&mut self);
+
+    /// Borrows the elements as a slice
+    fn as_slice(&self) -> &[T];
+
+    /// Borrows the elements as a mutable slice
+    fn as_mut_slice(&mut self) -> &mut [T];
+}
+
+impl<T> BatchStorage<T> for Vec<T> {
+    fn with_capacity(capacity: usize) -> Self {
+        Vec::with_capacity(capacity)
+    }
+
+    fn push(&mut self, value: T) {
+        Vec::push(self, value)
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn clear(&mut self) {
+        Vec::clear(self)
+    }
+
+    fn as_slice(&self) -> &[T] {
+        Vec::as_slice(self)
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        Vec::as_mut_slice(self)
+    }
+}
+
+/// Integer types that can be delta + zigzag + varint encoded by
+/// [`RemedianBlock::compress_locked_row`]
+///
+/// Implemented for the built-in signed integer types, by round-tripping through `i128`. This is
+/// a conversion trait rather than a storage backend, so it composes with any [`BatchStorage`].
+pub trait VarintInt: Copy {
+    /// Widens `self` to an `i128`, losslessly, for delta encoding
+    fn to_i128(self) -> i128;
+
+    /// Narrows an `i128` produced by [`Self::to_i128`] back to `Self`
+    fn from_i128(value: i128) -> Self;
+}
+
+macro_rules! impl_varint_int {
+    ($($t:ty),*) => {
+        $(
+            impl VarintInt for $t {
+                fn to_i128(self) -> i128 {
+                    self as i128
+                }
+
+                fn from_i128(value: i128) -> Self {
+                    value as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_varint_int!(i8, i16, i32, i64, i128, isize);
+
+/// Zigzag-encodes a signed `i128` so small magnitudes (positive or negative) map to small
+/// unsigned values, suitable for [`write_varint`]
+fn zigzag_encode(value: i128) -> u128 {
+    ((value << 1) ^ (value >> 127)) as u128
+}
+
+/// Reverses [`zigzag_encode`]
+fn zigzag_decode(value: u128) -> i128 {
+    ((value >> 1) as i128) ^ -((value & 1) as i128)
+}
+
+/// Appends `value` to `buf` as a LEB128 variable-length integer: 7 data bits per byte, with the
+/// high bit set on every byte but the last
+fn write_varint(buf: &mut Vec<u8>, mut value: u128) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            break;
+        } else {
+            buf.push(byte | 0x80);
+        }
+    }
+}
+
+/// Reads one LEB128 variable-length integer from `bytes` starting at `*pos`, advancing `*pos`
+/// past it
+fn read_varint(bytes: &[u8], pos: &mut usize) -> u128 {
+    let mut result: u128 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*pos];
+        *pos += 1;
+        result |= ((byte & 0x7f) as u128) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    result
+}
+
+/// Delta + zigzag + varint encodes a sorted slice of values, as used by
+/// [`RemedianBlock::compress_locked_row`]
+fn encode_deltas(values: &[i128]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut prev = 0i128;
+    for &value in values {
+        write_varint(&mut buf, zigzag_encode(value.wrapping_sub(prev)));
+        prev = value;
+    }
+    buf
+}
+
+/// Decodes `count` values from a buffer produced by [`encode_deltas`], re-summing each delta
+/// onto a running total
+fn decode_deltas(bytes: &[u8], count: usize) -> Vec<i128> {
+    let mut pos = 0;
+    let mut value = 0i128;
+    let mut values = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        value = value.wrapping_add(zigzag_decode(read_varint(bytes, &mut pos)));
+        values.push(value);
+    }
+
+    values
+}
+
+/// Floors a non-negative, finite `f64`
+///
+/// `f64::floor` is a `std`-only inherent method, so this crate provides its own under
+/// `no_std`: casting to `u64` already truncates toward zero, which is the floor for any
+/// non-negative input.
+fn floor_non_negative(value: f64) -> f64 {
+    (value as u64) as f64
+}
+
+/// Rounds a non-negative, finite `f64` to the nearest integer, ties away from zero
+///
+/// `f64::round` is a `std`-only inherent method; see [`floor_non_negative`].
+fn round_half_up(value: f64) -> f64 {
+    floor_non_negative(value + 0.5)
+}
+
+#[cfg(feature = "smallvec")]
+impl<A: smallvec::Array> BatchStorage<A::Item> for smallvec::SmallVec<A> {
+    fn with_capacity(capacity: usize) -> Self {
+        smallvec::SmallVec::with_capacity(capacity)
+    }
+
+    fn push(&mut self, value: A::Item) {
+        smallvec::SmallVec::push(self, value)
+    }
+
+    fn len(&self) -> usize {
+        smallvec::SmallVec::len(self)
+    }
+
+    fn clear(&mut self) {
+        smallvec::SmallVec::clear(self)
+    }
+
+    fn as_slice(&self) -> &[A::Item] {
+        smallvec::SmallVec::as_slice(self)
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [A::Item] {
+        smallvec::SmallVec::as_mut_slice(self)
+    }
+}
 
 /// Current remedian state calculated for a data stream with data of type `T`
 ///
@@ -11,8 +232,12 @@ use std::cmp::Ordering;
 ///
 /// The maximum number of collectable sample points is equal to `remedian_base ^ remedian_exponent`.
 /// After this many points have been collected, the block will be **locked**, and [`Self::add_sample_point`] will be a no-op.
+///
+/// The `S` type parameter controls how each batch row in the scratch matrix is stored, and
+/// defaults to [`Vec`]. See [`BatchStorage`] for details, including the allocation-free
+/// `smallvec`-backed alternative.
 #[derive(Debug, Clone)]
-pub struct RemedianBlock<T: PartialOrd + Clone> {
+pub struct RemedianBlock<T: PartialOrd + Clone, S: BatchStorage<T> = Vec<T>> {
     /// Base value to use for calculating the remedian
     ///
     /// This should always be an odd number, as it makes the calculation faster
@@ -26,7 +251,27 @@ pub struct RemedianBlock<T: PartialOrd + Clone> {
     /// A [`Self::remedian_base`]*[`Self::remedian_exponent`] scratch matrix used for calculating the median
     ///
     /// A scratch matrix of this size gives us a sample size of [`Self::remedian_base`]^[`Self::remedian_exponent`]
-    remedian_scratch: Vec<Vec<T>>,
+    remedian_scratch: Vec<S>,
+
+    /// Run-length encoded scratch rows used instead of [`Self::remedian_scratch`] when this
+    /// block was constructed with [`Self::new_counting`]
+    ///
+    /// Each row holds `(value, count)` pairs sorted by value, so repeated values in
+    /// low-cardinality streams cost one slot each rather than one slot per sample point.
+    /// `None` for blocks constructed via [`Self::new`].
+    counting_scratch: Option<Vec<Vec<(T, u32)>>>,
+
+    /// Element count and delta + zigzag + varint encoded bytes for the final scratch row, set via
+    /// [`Self::compress_locked_row`] once [`Self::locked`]
+    ///
+    /// The element count is stored alongside the bytes because `merge` can leave the final row
+    /// holding more than `remedian_base` elements, so it can't be recovered from
+    /// `remedian_base` alone at decode time.
+    ///
+    /// While `Some`, the final row in `remedian_scratch` is left empty; call
+    /// [`Self::decompress_locked_row`] to restore it before reading the median or quantile again.
+    /// `None` for blocks that have never had [`Self::compress_locked_row`] called.
+    compressed_locked_row: Option<(usize, Vec<u8>)>,
 
     /// Flag for whether the `remedian_scratch` is full
     ///
@@ -35,7 +280,7 @@ pub struct RemedianBlock<T: PartialOrd + Clone> {
     locked: bool,
 }
 
-impl<T: PartialOrd + Clone> Default for RemedianBlock<T> {
+impl<T: PartialOrd + Clone, S: BatchStorage<T>> Default for RemedianBlock<T, S> {
     /// Initializes a remedian block with a base value of 11 and an exponent of 10.
     ///
     /// This is a reasonable default for most applications, and provides room for roughly 25 billion sample points.
@@ -44,7 +289,7 @@ impl<T: PartialOrd + Clone> Default for RemedianBlock<T> {
     }
 }
 
-impl<T: PartialOrd + Clone> RemedianBlock<T> {
+impl<T: PartialOrd + Clone, S: BatchStorage<T>> RemedianBlock<T, S> {
     /// Constructs a new [`Self`], without any sample points collected
     ///
     /// Inputs:
@@ -54,14 +299,14 @@ impl<T: PartialOrd + Clone> RemedianBlock<T> {
     /// See the struct-level docs for more information.
     /// If you are unsure of what to use, [`Self::default`] provides reasonable defaults.
     pub fn new(remedian_base: usize, remedian_exponent: usize) -> Self {
-        if remedian_base % 2 == 0 {
+        if remedian_base.is_multiple_of(2) {
             #[cfg(feature = "logging")]
             log::warn!(
                 "Got even remedian base: {}. This will result in inaccuracies.",
                 remedian_base
             );
 
-            #[cfg(not(feature = "logging"))]
+            #[cfg(all(not(feature = "logging"), feature = "std"))]
             eprintln!(
                 "Got even remedian base: {}. This will result in inaccuracies.",
                 remedian_base
@@ -70,7 +315,7 @@ impl<T: PartialOrd + Clone> RemedianBlock<T> {
 
         let mut remedian_scratch = Vec::with_capacity(remedian_exponent);
         for _ in 0..remedian_exponent {
-            remedian_scratch.push(Vec::with_capacity(remedian_base));
+            remedian_scratch.push(S::with_capacity(remedian_base));
         }
 
         Self {
@@ -78,6 +323,8 @@ impl<T: PartialOrd + Clone> RemedianBlock<T> {
             remedian_exponent,
             count: 0,
             remedian_scratch,
+            counting_scratch: None,
+            compressed_locked_row: None,
             locked: false,
         }
     }
@@ -99,8 +346,36 @@ impl<T: PartialOrd + Clone> RemedianBlock<T> {
     ///
     /// Returns whether the point was actually added
     pub fn add_sample_point(&mut self, sample_point: T) -> bool {
-        if !self.locked {
-            self.count += 1;
+        if self.locked {
+            return false;
+        }
+
+        self.count += 1;
+
+        if let Some(scratch) = self.counting_scratch.as_mut() {
+            // Run-length mode: ripple-carry the weighted-middle entry of each row upward,
+            // same as below, but counting duplicate values instead of storing them again
+            let mut carry = Some(sample_point);
+
+            let last = self.remedian_exponent - 1;
+            for (i, row) in scratch.iter_mut().enumerate() {
+                let Some(value) = carry.take() else {
+                    break;
+                };
+
+                Self::insert_counting(row, value);
+
+                let row_count: u32 = row.iter().map(|(_, count)| *count).sum();
+                if row_count == self.remedian_base as u32 {
+                    if i == last {
+                        self.locked = true;
+                    } else {
+                        carry = Some(Self::weighted_middle(row, self.remedian_base));
+                        row.clear();
+                    }
+                }
+            }
+        } else {
             self.remedian_scratch[0].push(sample_point);
 
             // Check each batch to see if it's full, carrying intermediate medians to the next batch until
@@ -115,14 +390,18 @@ impl<T: PartialOrd + Clone> RemedianBlock<T> {
                         // This is the last batch, so there's no where to carry to
                         // Lock the scratch and call it a day
 
-                        batch.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                        batch
+                            .as_mut_slice()
+                            .sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
                         self.locked = true;
                     } else {
                         // Not the last batch yet, so calculate the intermediate median,
                         // carry it to the next batch, and empty the batch
 
-                        batch.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
-                        let intermediate_median = batch[self.remedian_base / 2].clone();
+                        batch
+                            .as_mut_slice()
+                            .sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                        let intermediate_median = batch.as_slice()[self.remedian_base / 2].clone();
                         batch.clear();
 
                         self.remedian_scratch[i + 1].push(intermediate_median);
@@ -132,10 +411,189 @@ impl<T: PartialOrd + Clone> RemedianBlock<T> {
                     break;
                 }
             }
+        }
 
-            true
-        } else {
-            false
+        true
+    }
+
+    /// Inserts `value` into a run-length encoded row, maintained sorted by value via binary
+    /// search, bumping the count of an existing entry instead of storing the value again
+    fn insert_counting(row: &mut Vec<(T, u32)>, value: T) {
+        match row.binary_search_by(|(v, _)| v.partial_cmp(&value).unwrap_or(Ordering::Equal)) {
+            Ok(idx) => row[idx].1 += 1,
+            Err(idx) => row.insert(idx, (value, 1)),
+        }
+    }
+
+    /// Finds the value at the weighted middle (index `remedian_base / 2`) of a full run-length
+    /// encoded row, accounting for each entry's count
+    fn weighted_middle(row: &[(T, u32)], remedian_base: usize) -> T {
+        Self::counting_index(row, (remedian_base / 2) as u32)
+    }
+
+    /// Finds the value at flat index `index` of a run-length encoded row, accounting for each
+    /// entry's count
+    fn counting_index(row: &[(T, u32)], index: u32) -> T {
+        let mut cumulative = 0;
+
+        for (value, count) in row {
+            cumulative += count;
+            if cumulative > index {
+                return value.clone();
+            }
+        }
+
+        row.last()
+            .expect("a full row is never empty")
+            .0
+            .clone()
+    }
+
+    /// Merges `other` into `self`, for combining blocks built from separate shards of a stream
+    ///
+    /// Both blocks must share the same `remedian_base` and `remedian_exponent`. Implemented by
+    /// concatenating each pair of corresponding scratch rows and re-running the same
+    /// ripple-carry logic as [`Self::add_sample_point`], so a row that overflows after merging
+    /// may carry its weighted-middle entry upward more than once. If the final row overflows,
+    /// the merged block becomes locked, with its (possibly oversized) final row sorted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self` and `other` have different `remedian_base` or `remedian_exponent`, if
+    /// one block was constructed with [`Self::new_counting`] and the other was not, or if either
+    /// block currently has a compressed final row (see [`Self::compress_locked_row`]).
+    pub fn merge(&mut self, other: RemedianBlock<T, S>) {
+        assert_eq!(
+            self.remedian_base, other.remedian_base,
+            "cannot merge remedian blocks with different remedian_base"
+        );
+        assert_eq!(
+            self.remedian_exponent, other.remedian_exponent,
+            "cannot merge remedian blocks with different remedian_exponent"
+        );
+        assert!(
+            self.compressed_locked_row.is_none() && other.compressed_locked_row.is_none(),
+            "cannot merge a block with a compressed final row; call decompress_locked_row first"
+        );
+
+        self.count += other.count;
+        let remedian_base = self.remedian_base;
+        let exponent = self.remedian_exponent;
+
+        match (self.counting_scratch.as_mut(), other.counting_scratch) {
+            (Some(self_scratch), Some(other_scratch)) => {
+                let mut carry: Vec<T> = Vec::new();
+
+                for (i, other_row) in other_scratch.into_iter().enumerate() {
+                    let mut combined =
+                        Self::merge_counting_lists(mem::take(&mut self_scratch[i]), other_row);
+                    for value in carry.drain(..) {
+                        Self::insert_counting(&mut combined, value);
+                    }
+
+                    if i == exponent - 1 {
+                        let row_count: u32 = combined.iter().map(|(_, count)| *count).sum();
+                        if row_count >= remedian_base as u32 {
+                            self.locked = true;
+                        }
+                        self_scratch[i] = combined;
+                    } else {
+                        let mut next_carry = Vec::new();
+                        while combined.iter().map(|(_, count)| *count).sum::<u32>()
+                            >= remedian_base as u32
+                        {
+                            next_carry.push(Self::weighted_middle(&combined, remedian_base));
+                            Self::drain_counting_base(&mut combined, remedian_base);
+                        }
+                        self_scratch[i] = combined;
+                        carry = next_carry;
+                    }
+                }
+            }
+            (None, None) => {
+                let mut carry: Vec<T> = Vec::new();
+
+                for (i, other_row) in other.remedian_scratch.into_iter().enumerate() {
+                    let mut combined: Vec<T> = self.remedian_scratch[i].as_slice().to_vec();
+                    combined.extend(other_row.as_slice().iter().cloned());
+                    combined.append(&mut carry);
+
+                    if i == exponent - 1 {
+                        if combined.len() >= remedian_base {
+                            combined.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                            self.locked = true;
+                        }
+                        self.remedian_scratch[i] = Self::row_from_vec(combined);
+                    } else {
+                        let mut next_carry = Vec::new();
+                        while combined.len() >= remedian_base {
+                            combined.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                            next_carry.push(combined[remedian_base / 2].clone());
+                            combined.drain(0..remedian_base);
+                        }
+                        self.remedian_scratch[i] = Self::row_from_vec(combined);
+                        carry = next_carry;
+                    }
+                }
+            }
+            _ => panic!("cannot merge remedian blocks using different storage modes"),
+        }
+    }
+
+    /// Builds a fresh `S` from a plain [`Vec`] of values, used by [`Self::merge`] to rebuild
+    /// scratch rows after combining them
+    fn row_from_vec(values: Vec<T>) -> S {
+        let mut row = S::with_capacity(values.len());
+        for value in values {
+            row.push(value);
+        }
+        row
+    }
+
+    /// Merges two sorted run-length encoded rows into one, summing the counts of equal values
+    fn merge_counting_lists(a: Vec<(T, u32)>, b: Vec<(T, u32)>) -> Vec<(T, u32)> {
+        let mut result = Vec::with_capacity(a.len() + b.len());
+        let mut a_iter = a.into_iter().peekable();
+        let mut b_iter = b.into_iter().peekable();
+
+        loop {
+            match (a_iter.peek(), b_iter.peek()) {
+                (Some((a_value, _)), Some((b_value, _))) => {
+                    match a_value.partial_cmp(b_value).unwrap_or(Ordering::Equal) {
+                        Ordering::Less => result.push(a_iter.next().unwrap()),
+                        Ordering::Greater => result.push(b_iter.next().unwrap()),
+                        Ordering::Equal => {
+                            let (value, a_count) = a_iter.next().unwrap();
+                            let (_, b_count) = b_iter.next().unwrap();
+                            result.push((value, a_count + b_count));
+                        }
+                    }
+                }
+                (Some(_), None) => result.push(a_iter.next().unwrap()),
+                (None, Some(_)) => result.push(b_iter.next().unwrap()),
+                (None, None) => break,
+            }
+        }
+
+        result
+    }
+
+    /// Removes the front `remedian_base` count-units from a sorted run-length encoded row,
+    /// after its weighted middle has been carried upward
+    fn drain_counting_base(row: &mut Vec<(T, u32)>, remedian_base: usize) {
+        let mut remaining = remedian_base as u32;
+        while remaining > 0 {
+            let Some(front) = row.first_mut() else {
+                break;
+            };
+
+            if front.1 <= remaining {
+                remaining -= front.1;
+                row.remove(0);
+            } else {
+                front.1 -= remaining;
+                remaining = 0;
+            }
         }
     }
 
@@ -144,39 +602,336 @@ impl<T: PartialOrd + Clone> RemedianBlock<T> {
     /// If no data has been processed, this returns [`None`].
     /// Otherwise, a value is always returned.
     /// For an unchecked version, use [`Self::median_or_default`].
+    ///
+    /// Equivalent to [`Self::quantile`]`(0.5)`. When the true median falls between two distinct
+    /// values, this collapses to the lower of the two. Use [`Self::median_pair`] to get both.
     pub fn median(&self) -> Option<T> {
+        self.quantile(0.5)
+    }
+
+    /// Gets the approximate `p`-quantile of the data points processed, for `p` in `[0, 1]`
+    ///
+    /// `p` is clamped into `[0, 1]`. If no data has been processed, this returns [`None`].
+    /// `quantile(0.5)` is equivalent to [`Self::median`].
+    ///
+    /// Accuracy degrades for `p` near the extremes (close to `0` or `1`), since the values
+    /// carried upward through the scratch matrix track the center of each batch rather than
+    /// its tails.
+    pub fn quantile(&self, p: f64) -> Option<T> {
+        let p = p.clamp(0., 1.);
+
+        if let Some(scratch) = &self.counting_scratch {
+            return if self.locked {
+                let index = round_half_up(p * (self.remedian_base - 1) as f64) as u32;
+                Some(Self::counting_index(
+                    &scratch[self.remedian_exponent - 1],
+                    index,
+                ))
+            } else {
+                let mut weighted_values = Vec::new();
+                for (i, row) in scratch.iter().enumerate() {
+                    for (value, count) in row {
+                        weighted_values
+                            .push((value, (self.remedian_base as u64).pow(i as u32) * *count as u64));
+                    }
+                }
+
+                Self::quantile_scan(weighted_values, p, self.count)
+            };
+        }
+
         if self.locked {
+            assert!(
+                self.compressed_locked_row.is_none(),
+                "final scratch row is compressed; call decompress_locked_row first"
+            );
+
+            // We filled our maximum samples, so just index into the final batch
+            // Note that it's sorted in `add_sample_point` above
+            let index = round_half_up(p * (self.remedian_base - 1) as f64) as usize;
+            Some(self.remedian_scratch[self.remedian_exponent - 1].as_slice()[index].clone())
+        } else {
+            // Not all the batches are full, so calculate a weighted quantile based on what we have
+
+            let mut weighted_values = Vec::new();
+            for (i, batch) in self.remedian_scratch.iter().enumerate() {
+                for m in batch.as_slice().iter() {
+                    weighted_values.push((m, (self.remedian_base as u64).pow(i as u32)));
+                }
+            }
+
+            Self::quantile_scan(weighted_values, p, self.count)
+        }
+    }
+
+    /// Finds the value whose cumulative weight reaches the `p`-quantile's target weight, without
+    /// fully sorting `weighted_values`
+    ///
+    /// Degenerate case where no data has been processed returns [`None`].
+    fn quantile_scan(weighted_values: Vec<(&T, u64)>, p: f64, count: u64) -> Option<T> {
+        // Floored so that `p = 0.5` reaches exactly the same target weight as `median_pair`'s
+        // `count / 2`, for both even and odd `count`
+        let target = floor_non_negative(p * count as f64) as u64;
+
+        Self::weighted_select(weighted_values, target)
+    }
+
+    /// Finds the value at the given weighted rank among `items` (sorted ascending by value),
+    /// without fully sorting `items`
+    ///
+    /// `target_weight` is clamped into `[1, total_weight]`, since a running weight starting at
+    /// zero always reaches a target of zero or one at the smallest value present. Returns
+    /// [`None`] if `items` is empty.
+    fn weighted_select(items: Vec<(&T, u64)>, target_weight: u64) -> Option<T> {
+        if items.is_empty() {
+            return None;
+        }
+
+        let total_weight: u64 = items.iter().map(|(_, weight)| weight).sum();
+        let target_weight = target_weight.clamp(1, total_weight);
+
+        Some(Self::select_rank(items, target_weight))
+    }
+
+    /// Recursively selects the value at weighted rank `target_weight` among `items`, via
+    /// median-of-medians quickselect
+    ///
+    /// `target_weight` must be in `1..=` the total weight of `items`. Partitions around a pivot
+    /// chosen as the median of per-five-element group medians, which guarantees a good enough
+    /// split to run in `O(n)` comparisons overall, rather than the `O(n log n)` of a full sort.
+    /// Incomparable pairs are treated as equal, so this only requires `T: PartialOrd`.
+    fn select_rank(mut items: Vec<(&T, u64)>, target_weight: u64) -> T {
+        if items.len() == 1 {
+            return items[0].0.clone();
+        }
+
+        let pivot = Self::median_of_medians(&items);
+
+        let mut less = Vec::new();
+        let mut greater = Vec::new();
+        let mut less_weight = 0;
+        let mut equal_weight = 0;
+
+        for (value, weight) in items.drain(..) {
+            match value.partial_cmp(&pivot).unwrap_or(Ordering::Equal) {
+                Ordering::Less => {
+                    less_weight += weight;
+                    less.push((value, weight));
+                }
+                Ordering::Equal => equal_weight += weight,
+                Ordering::Greater => greater.push((value, weight)),
+            }
+        }
+
+        if target_weight <= less_weight {
+            Self::select_rank(less, target_weight)
+        } else if target_weight <= less_weight + equal_weight {
+            pivot
+        } else {
+            Self::select_rank(greater, target_weight - less_weight - equal_weight)
+        }
+    }
+
+    /// Picks a pivot for [`Self::select_rank`]: partitions `items` into groups of (at most) five,
+    /// takes each group's median by direct comparison, then recursively finds the median of
+    /// those group medians
+    fn median_of_medians(items: &[(&T, u64)]) -> T {
+        if items.len() <= 5 {
+            return Self::median_of_small_group(items.iter().map(|(value, _)| *value));
+        }
+
+        let medians: Vec<T> = items
+            .chunks(5)
+            .map(|chunk| Self::median_of_small_group(chunk.iter().map(|(value, _)| *value)))
+            .collect();
+
+        let weighted_medians: Vec<(&T, u64)> = medians.iter().map(|median| (median, 1)).collect();
+        let target = (weighted_medians.len() as u64).div_ceil(2);
+
+        Self::select_rank(weighted_medians, target)
+    }
+
+    /// Finds the median of a group of (at most) five values by direct comparison
+    fn median_of_small_group<'a>(group: impl Iterator<Item = &'a T>) -> T
+    where
+        T: 'a,
+    {
+        let mut group: Vec<&T> = group.collect();
+        group.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        group[group.len() / 2].clone()
+    }
+
+    /// Gets the approximate median of the data points processed, as a [`MedianResult`]
+    ///
+    /// If no data has been processed, this returns [`None`].
+    /// Otherwise, a [`MedianResult::One`] is returned, unless the block is not locked, an even
+    /// number of sample points has been collected and the two middle values are distinct, in
+    /// which case a [`MedianResult::Two`] is returned instead. A locked block always returns
+    /// [`MedianResult::One`], taken from its final row.
+    pub fn median_pair(&self) -> Option<MedianResult<T>> {
+        if let Some(scratch) = &self.counting_scratch {
+            return if self.locked {
+                // We filled our maximum samples, so just take the weighted middle of the final
+                // row, accounting for its per-value counts
+                Some(MedianResult::One(Self::weighted_middle(
+                    &scratch[self.remedian_exponent - 1],
+                    self.remedian_base,
+                )))
+            } else {
+                let mut weighted_values = Vec::new();
+                for (i, row) in scratch.iter().enumerate() {
+                    for (value, count) in row {
+                        weighted_values
+                            .push((value, (self.remedian_base as u64).pow(i as u32) * *count as u64));
+                    }
+                }
+
+                self.weighted_scan(weighted_values)
+            };
+        }
+
+        if self.locked {
+            assert!(
+                self.compressed_locked_row.is_none(),
+                "final scratch row is compressed; call decompress_locked_row first"
+            );
+
             // We filled our maximum samples, so just take the median of the final batch
             // Note that it's sorted in `add_sample_point` above
-            Some(self.remedian_scratch[self.remedian_exponent - 1][self.remedian_base / 2].clone())
+            Some(MedianResult::One(
+                self.remedian_scratch[self.remedian_exponent - 1].as_slice()
+                    [self.remedian_base / 2]
+                    .clone(),
+            ))
         } else {
             // Not all the batches are full, so calculate a weighted median based on what we have
 
             let mut weighted_values = Vec::new();
             for (i, batch) in self.remedian_scratch.iter().enumerate() {
-                for m in batch.iter() {
+                for m in batch.as_slice().iter() {
                     weighted_values.push((m, (self.remedian_base as u64).pow(i as u32)));
                 }
             }
 
-            weighted_values.sort_by(|a, b| a.0.partial_cmp(b.0).unwrap_or(Ordering::Equal));
+            self.weighted_scan(weighted_values)
+        }
+    }
 
-            let mut running_weight = 0;
-            for (m, w) in weighted_values.into_iter() {
-                running_weight += w;
-                if running_weight >= (self.count / 2) {
-                    return Some(m.clone());
+    /// Finds the weighted-median crossing point among value/weight pairs, shared by the plain
+    /// and run-length scratch representations
+    ///
+    /// Degenerate case where no data has been processed returns [`None`].
+    fn weighted_scan(&self, weighted_values: Vec<(&T, u64)>) -> Option<MedianResult<T>> {
+        if weighted_values.is_empty() {
+            return None;
+        }
+
+        let target = self.count / 2;
+        let primary = Self::weighted_select(weighted_values.clone(), target)?;
+
+        if self.count.is_multiple_of(2) {
+            // If the running weight up to and including `primary` lands exactly on the
+            // boundary, the true median straddles `primary` and the next distinct value
+            let weight_up_to_primary: u64 = weighted_values
+                .iter()
+                .filter(|(value, _)| (*value).partial_cmp(&primary).unwrap_or(Ordering::Equal) != Ordering::Greater)
+                .map(|(_, weight)| weight)
+                .sum();
+
+            if weight_up_to_primary == target {
+                let greater: Vec<(&T, u64)> = weighted_values
+                    .into_iter()
+                    .filter(|(value, _)| (*value).partial_cmp(&primary).unwrap_or(Ordering::Equal) == Ordering::Greater)
+                    .collect();
+
+                if let Some(next) = Self::weighted_select(greater, 1) {
+                    return Some(MedianResult::Two(primary, next));
                 }
             }
+        }
 
-            // Degenerate case where no data has been processed
-            // Return None
-            None
+        Some(MedianResult::One(primary))
+    }
+}
+
+impl<T: Ord + Clone, S: BatchStorage<T>> RemedianBlock<T, S> {
+    /// Constructs a new [`Self`] backed by run-length encoded batches, for low-cardinality streams
+    ///
+    /// Instead of storing one slot per sample point, each scratch row holds `(value, count)`
+    /// pairs sorted by value, maintained via binary search on insert. This is far cheaper in
+    /// both time and space than [`Self::new`] for streams over a small set of distinct values
+    /// (e.g. bucketed integers, or a low-cardinality enum), at the cost of requiring `T: Ord`.
+    ///
+    /// Inputs are the same as [`Self::new`].
+    pub fn new_counting(remedian_base: usize, remedian_exponent: usize) -> Self {
+        let mut block = Self::new(remedian_base, remedian_exponent);
+        block.counting_scratch = Some((0..remedian_exponent).map(|_| Vec::new()).collect());
+        block
+    }
+}
+
+impl<T: PartialOrd + Clone + VarintInt, S: BatchStorage<T>> RemedianBlock<T, S> {
+    /// Compresses the locked final scratch row into a delta + zigzag + varint encoded byte
+    /// buffer, freeing the row's backing storage
+    ///
+    /// The scratch matrix is the dominant memory cost at large `remedian_exponent` values, and
+    /// the final row is the only one that persists for the lifetime of a locked block (every
+    /// other row is cleared as soon as its weighted middle is carried upward). Since the final
+    /// row is sorted and never mutated again once locked, it compresses well: each value is
+    /// stored as a zigzag-encoded delta from its predecessor, varint-encoded, which is compact
+    /// for monotone-ish or clustered integer streams.
+    ///
+    /// This trades a little CPU for a large reduction in memory: [`Self::median`],
+    /// [`Self::quantile`], and [`Self::median_pair`] all panic while the final row is
+    /// compressed, so call [`Self::decompress_locked_row`] first if you need to read the median
+    /// again after compressing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block is not yet [`Self::locked`], or if it was constructed with
+    /// [`Self::new_counting`] (the final row then lives in the run-length encoded scratch,
+    /// which this does not compress).
+    pub fn compress_locked_row(&mut self) {
+        assert!(
+            self.locked,
+            "cannot compress the final scratch row of an unlocked block"
+        );
+        assert!(
+            self.counting_scratch.is_none(),
+            "cannot compress the final scratch row of a counting block"
+        );
+
+        let last = self.remedian_exponent - 1;
+        let values: Vec<i128> = self.remedian_scratch[last]
+            .as_slice()
+            .iter()
+            .map(|value| value.to_i128())
+            .collect();
+
+        self.compressed_locked_row = Some((values.len(), encode_deltas(&values)));
+        self.remedian_scratch[last] = S::with_capacity(0);
+    }
+
+    /// Reverses [`Self::compress_locked_row`], decoding the compressed byte buffer back into the
+    /// final scratch row
+    ///
+    /// A no-op if the row isn't currently compressed.
+    pub fn decompress_locked_row(&mut self) {
+        let Some((count, bytes)) = self.compressed_locked_row.take() else {
+            return;
+        };
+
+        let last = self.remedian_exponent - 1;
+        let mut row = S::with_capacity(count);
+        for value in decode_deltas(&bytes, count) {
+            row.push(T::from_i128(value));
         }
+
+        self.remedian_scratch[last] = row;
     }
 }
 
-impl<T: PartialOrd + Clone + Default> RemedianBlock<T> {
+impl<T: PartialOrd + Clone + Default, S: BatchStorage<T>> RemedianBlock<T, S> {
     /// Gets the approxmate median of the data points processed
     ///
     /// If no data has been processed, this returns `T::default()`.
@@ -216,7 +971,7 @@ mod tests {
 
     #[test]
     fn median_not_full() {
-        let mut remedian = RemedianBlock::default();
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::default();
 
         for v in load_test_data().into_iter() {
             remedian.add_sample_point(v);
@@ -227,7 +982,7 @@ mod tests {
 
     #[test]
     fn median_full() {
-        let mut remedian = RemedianBlock::new(11, 3);
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::new(11, 3);
 
         for v in load_test_data().into_iter() {
             remedian.add_sample_point(v);
@@ -238,7 +993,7 @@ mod tests {
 
     #[test]
     fn locked_not_full() {
-        let mut remedian = RemedianBlock::default();
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::default();
 
         for v in load_test_data().into_iter() {
             remedian.add_sample_point(v);
@@ -249,7 +1004,7 @@ mod tests {
 
     #[test]
     fn locked_full() {
-        let mut remedian = RemedianBlock::new(11, 3);
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::new(11, 3);
 
         for v in load_test_data().into_iter() {
             remedian.add_sample_point(v);
@@ -260,7 +1015,7 @@ mod tests {
 
     #[test]
     fn count_not_full() {
-        let mut remedian = RemedianBlock::default();
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::default();
 
         for v in load_test_data().into_iter() {
             remedian.add_sample_point(v);
@@ -271,7 +1026,7 @@ mod tests {
 
     #[test]
     fn count_full() {
-        let mut remedian = RemedianBlock::new(11, 3);
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::new(11, 3);
 
         for v in load_test_data().into_iter() {
             remedian.add_sample_point(v);
@@ -290,11 +1045,262 @@ mod tests {
 
     #[test]
     fn one_data() {
-        let mut remedian = RemedianBlock::default();
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::default();
         remedian.add_sample_point(10.);
 
         assert_eq!(remedian.median(), Some(10.));
         assert_eq!(remedian.count(), 1);
         assert!(!remedian.locked())
     }
+
+    #[test]
+    fn median_pair_even_distinct() {
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::default();
+        remedian.add_sample_point(10.);
+        remedian.add_sample_point(20.);
+
+        assert_eq!(remedian.median_pair(), Some(MedianResult::Two(10., 20.)));
+        assert_eq!(remedian.median(), Some(10.));
+    }
+
+    #[test]
+    fn median_pair_odd() {
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::default();
+        remedian.add_sample_point(10.);
+        remedian.add_sample_point(20.);
+        remedian.add_sample_point(30.);
+
+        assert_eq!(remedian.median_pair(), Some(MedianResult::One(10.)));
+    }
+
+    #[test]
+    fn quantile_on_locked_block() {
+        let mut remedian: RemedianBlock<i32> = RemedianBlock::new(11, 1);
+        for v in 1..=11 {
+            remedian.add_sample_point(v);
+        }
+
+        assert!(remedian.locked());
+        assert_eq!(remedian.quantile(0.0), Some(1));
+        assert_eq!(remedian.quantile(0.5), Some(6));
+        assert_eq!(remedian.quantile(0.9), Some(10));
+        assert_eq!(remedian.quantile(1.0), Some(11));
+    }
+
+    #[test]
+    fn quantile_matches_median_at_p_half() {
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::default();
+        for v in load_test_data().into_iter() {
+            remedian.add_sample_point(v);
+        }
+
+        assert_eq!(remedian.quantile(0.5), remedian.median());
+    }
+
+    #[test]
+    fn quantile_clamps_out_of_range_p() {
+        let mut remedian: RemedianBlock<i32> = RemedianBlock::new(11, 1);
+        for v in 1..=11 {
+            remedian.add_sample_point(v);
+        }
+
+        assert_eq!(remedian.quantile(-1.), remedian.quantile(0.));
+        assert_eq!(remedian.quantile(2.), remedian.quantile(1.));
+    }
+
+    #[test]
+    fn median_on_large_unlocked_stream() {
+        // Exercises the median-of-medians selection path (`>` 5 distinct entries) rather than
+        // the small-group base case
+        let mut remedian: RemedianBlock<f32> = RemedianBlock::default();
+
+        for v in load_test_data().into_iter() {
+            remedian.add_sample_point(v);
+        }
+
+        assert!((remedian.median_or_default() - EXPECTED_MEDIAN).abs() < MEDIAN_ERROR_LIMIT);
+    }
+
+    #[test]
+    fn merge_unlocked_blocks() {
+        let mut a: RemedianBlock<f64> = RemedianBlock::new(5, 2);
+        for v in [1., 2., 3., 4., 5.] {
+            a.add_sample_point(v);
+        }
+
+        let mut b: RemedianBlock<f64> = RemedianBlock::new(5, 2);
+        for v in [10., 20., 30., 40., 50.] {
+            b.add_sample_point(v);
+        }
+
+        a.merge(b);
+
+        assert_eq!(a.count(), 10);
+        assert!(!a.locked());
+        assert_eq!(a.median(), Some(3.));
+    }
+
+    #[test]
+    fn merge_overflows_and_locks() {
+        let mut a: RemedianBlock<f64> = RemedianBlock::new(3, 1);
+        for v in [5., 1.] {
+            a.add_sample_point(v);
+        }
+
+        let mut b: RemedianBlock<f64> = RemedianBlock::new(3, 1);
+        for v in [9., 2.] {
+            b.add_sample_point(v);
+        }
+
+        a.merge(b);
+
+        assert_eq!(a.count(), 4);
+        assert!(a.locked());
+        assert_eq!(a.median(), Some(2.));
+    }
+
+    #[test]
+    fn merge_counting_blocks_overflows_and_locks() {
+        let mut a: RemedianBlock<i32> = RemedianBlock::new_counting(3, 1);
+        for v in [5, 1] {
+            a.add_sample_point(v);
+        }
+
+        let mut b: RemedianBlock<i32> = RemedianBlock::new_counting(3, 1);
+        for v in [9, 2] {
+            b.add_sample_point(v);
+        }
+
+        a.merge(b);
+
+        assert_eq!(a.count(), 4);
+        assert!(a.locked());
+        assert_eq!(a.median(), Some(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "remedian_base")]
+    fn merge_rejects_mismatched_base() {
+        let mut a: RemedianBlock<f64> = RemedianBlock::new(5, 2);
+        let b: RemedianBlock<f64> = RemedianBlock::new(3, 2);
+        a.merge(b);
+    }
+
+    #[test]
+    fn counting_median_low_cardinality() {
+        let mut remedian: RemedianBlock<i32> = RemedianBlock::new_counting(11, 2);
+
+        // Heavily duplicated stream over a handful of distinct values
+        for _ in 0..40 {
+            remedian.add_sample_point(1);
+        }
+        for _ in 0..40 {
+            remedian.add_sample_point(2);
+        }
+        for _ in 0..40 {
+            remedian.add_sample_point(3);
+        }
+
+        assert_eq!(remedian.median(), Some(2));
+        assert_eq!(remedian.count(), 120);
+    }
+
+    #[test]
+    fn counting_median_locks() {
+        let mut remedian: RemedianBlock<i32> = RemedianBlock::new_counting(11, 1);
+
+        for _ in 0..11 {
+            remedian.add_sample_point(7);
+        }
+
+        assert!(remedian.locked());
+        assert_eq!(remedian.median(), Some(7));
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn smallvec_backed_block() {
+        let mut remedian: RemedianBlock<f32, smallvec::SmallVec<[f32; 11]>> =
+            RemedianBlock::new(11, 3);
+
+        for v in load_test_data().into_iter() {
+            remedian.add_sample_point(v);
+        }
+
+        assert!((remedian.median_or_default() - EXPECTED_MEDIAN).abs() < MEDIAN_ERROR_LIMIT);
+    }
+
+    #[test]
+    fn compress_and_decompress_locked_row() {
+        let mut remedian: RemedianBlock<i64> = RemedianBlock::new(11, 1);
+        for v in 1..=11i64 {
+            remedian.add_sample_point(v);
+        }
+
+        assert!(remedian.locked());
+        let before = remedian.median();
+
+        remedian.compress_locked_row();
+        remedian.decompress_locked_row();
+
+        assert_eq!(remedian.median(), before);
+        assert_eq!(remedian.median(), Some(6));
+    }
+
+    #[test]
+    fn compress_and_decompress_overflowed_row() {
+        // `merge` can leave the final row holding more than `remedian_base` elements; the
+        // compressed encoding must round-trip that count rather than assuming `remedian_base`
+        let mut a: RemedianBlock<i64> = RemedianBlock::new(3, 1);
+        for v in [5, 1] {
+            a.add_sample_point(v);
+        }
+
+        let mut b: RemedianBlock<i64> = RemedianBlock::new(3, 1);
+        for v in [9, 2] {
+            b.add_sample_point(v);
+        }
+
+        a.merge(b);
+        assert_eq!(a.count(), 4);
+        assert!(a.locked());
+
+        let before = a.median();
+
+        a.compress_locked_row();
+        a.decompress_locked_row();
+
+        assert_eq!(a.median(), before);
+        assert_eq!(a.median(), Some(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "compressed")]
+    fn median_rejects_compressed_row() {
+        let mut remedian: RemedianBlock<i64> = RemedianBlock::new(11, 1);
+        for v in 1..=11i64 {
+            remedian.add_sample_point(v);
+        }
+
+        remedian.compress_locked_row();
+        remedian.median();
+    }
+
+    #[test]
+    #[should_panic(expected = "unlocked")]
+    fn compress_rejects_unlocked_block() {
+        let mut remedian: RemedianBlock<i64> = RemedianBlock::new(11, 10);
+        remedian.add_sample_point(1i64);
+        remedian.compress_locked_row();
+    }
+
+    #[test]
+    #[should_panic(expected = "counting")]
+    fn compress_rejects_counting_block() {
+        let mut remedian: RemedianBlock<i64> = RemedianBlock::new_counting(11, 1);
+        for _ in 0..11 {
+            remedian.add_sample_point(1i64);
+        }
+        remedian.compress_locked_row();
+    }
 }